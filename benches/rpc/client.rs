@@ -0,0 +1,219 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! RPC load-generation and latency benchmark driver. Spins up `concurrency`
+//! client workers against `server_address`, each over its own connection
+//! issuing `call_remote` back to back (or at `target_rate`, open-loop when
+//! unset) until `duration_secs` elapses, then reports throughput and
+//! latency percentiles. Pairs with `benches/rpc/server.rs` as the target to
+//! drive.
+
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use sealfs::rpc::client::ClientAsync;
+use serde::Serialize;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "RPC load-generation and latency benchmark", long_about = None)]
+struct Args {
+    #[arg(long)]
+    server_address: String,
+    /// Number of concurrent client workers
+    #[arg(long, default_value_t = 16)]
+    concurrency: usize,
+    #[arg(long, default_value_t = 10)]
+    duration_secs: u64,
+    /// Size in bytes of the request payload
+    #[arg(long, default_value_t = 0)]
+    payload_size: usize,
+    #[arg(long, default_value_t = 0)]
+    operation_type: u32,
+    /// Target requests/sec per worker; 0 means open-loop, as fast as possible
+    #[arg(long, default_value_t = 0)]
+    target_rate: u64,
+    /// Write the machine-readable report to this path in addition to stdout
+    #[arg(long)]
+    json_out: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    hostname: String,
+    commit: String,
+    duration_secs: u64,
+    concurrency: usize,
+    requests: u64,
+    errors: u64,
+    bytes: u64,
+    requests_per_sec: f64,
+    bytes_per_sec: f64,
+    latency_ms: LatencyPercentiles,
+}
+
+#[derive(Debug, Serialize)]
+struct LatencyPercentiles {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+struct WorkerResult {
+    latencies: Vec<Duration>,
+    bytes: u64,
+    errors: u64,
+}
+
+#[tokio::main]
+pub async fn client() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let payload = vec![0u8; args.payload_size];
+    let deadline = Instant::now() + Duration::from_secs(args.duration_secs);
+    let min_gap = if args.target_rate > 0 {
+        Some(Duration::from_secs_f64(1.0 / args.target_rate as f64))
+    } else {
+        None
+    };
+
+    let mut workers = Vec::with_capacity(args.concurrency);
+    for _ in 0..args.concurrency {
+        // Each worker dials its own connection so `--concurrency` drives
+        // real concurrent load instead of serializing through one
+        // connection's mutex.
+        let client = ClientAsync::new();
+        client.add_connection(&args.server_address).await?;
+        let server_address = args.server_address.clone();
+        let payload = payload.clone();
+        let operation_type = args.operation_type;
+        workers.push(tokio::spawn(async move {
+            run_worker(client, server_address, payload, operation_type, deadline, min_gap).await
+        }));
+    }
+
+    let mut latencies = Vec::new();
+    let mut bytes = 0u64;
+    let mut errors = 0u64;
+    for worker in workers {
+        let result = worker.await?;
+        latencies.extend(result.latencies);
+        bytes += result.bytes;
+        errors += result.errors;
+    }
+    latencies.sort_unstable();
+
+    let report = Report {
+        hostname: std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string()),
+        commit: current_commit(),
+        duration_secs: args.duration_secs,
+        concurrency: args.concurrency,
+        requests: latencies.len() as u64,
+        errors,
+        bytes,
+        requests_per_sec: latencies.len() as f64 / args.duration_secs as f64,
+        bytes_per_sec: bytes as f64 / args.duration_secs as f64,
+        latency_ms: percentiles(&latencies),
+    };
+
+    let json = serde_json::to_string_pretty(&report)?;
+    println!("{json}");
+    if let Some(path) = args.json_out {
+        std::fs::write(path, json)?;
+    }
+
+    Ok(())
+}
+
+async fn run_worker(
+    client: ClientAsync,
+    server_address: String,
+    payload: Vec<u8>,
+    operation_type: u32,
+    deadline: Instant,
+    min_gap: Option<Duration>,
+) -> WorkerResult {
+    let mut latencies = Vec::new();
+    let mut bytes = 0u64;
+    let mut errors = 0u64;
+
+    while Instant::now() < deadline {
+        let start = Instant::now();
+        let mut status = 0i32;
+        let mut rsp_flags = 0u32;
+        let mut recv_meta_data_length = 0usize;
+        let mut recv_data_length = 0usize;
+        let result = client
+            .call_remote(
+                &server_address,
+                operation_type,
+                0,
+                "",
+                &payload,
+                &[],
+                &mut status,
+                &mut rsp_flags,
+                &mut recv_meta_data_length,
+                &mut recv_data_length,
+                &mut [],
+                &mut [],
+            )
+            .await;
+        let elapsed = start.elapsed();
+
+        match result {
+            Ok(()) => {
+                latencies.push(elapsed);
+                bytes += payload.len() as u64;
+            }
+            Err(_) => errors += 1,
+        }
+
+        if let Some(min_gap) = min_gap {
+            if let Some(remaining) = min_gap.checked_sub(elapsed) {
+                tokio::time::sleep(remaining).await;
+            }
+        }
+    }
+
+    WorkerResult {
+        latencies,
+        bytes,
+        errors,
+    }
+}
+
+fn percentiles(sorted_latencies: &[Duration]) -> LatencyPercentiles {
+    let at = |p: f64| -> f64 {
+        if sorted_latencies.is_empty() {
+            return 0.0;
+        }
+        let idx = ((sorted_latencies.len() - 1) as f64 * p).round() as usize;
+        sorted_latencies[idx].as_secs_f64() * 1000.0
+    };
+    LatencyPercentiles {
+        p50: at(0.50),
+        p90: at(0.90),
+        p99: at(0.99),
+        max: sorted_latencies
+            .last()
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0),
+    }
+}
+
+fn current_commit() -> String {
+    std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn main() -> anyhow::Result<()> {
+    client()
+}
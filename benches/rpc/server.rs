@@ -13,6 +13,12 @@ impl HelloHandler {
     }
 }
 
+impl Default for HelloHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // lazy_static::lazy_static! {
 //     static ref HELLO_COUNT: Arc<Mutex<u32>> = Arc::new(Mutex::new(0));
 // }
@@ -56,3 +62,7 @@ pub async fn server() -> anyhow::Result<()> {
     server.run().await?;
     Ok(())
 }
+
+fn main() -> anyhow::Result<()> {
+    server()
+}
@@ -0,0 +1,54 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use log::info;
+use sealfs::manager;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1:9001")]
+    manager_address: String,
+    /// How often to check for servers that have gone quiet past their declared lifetime
+    #[arg(long, default_value_t = 5)]
+    reap_interval_secs: u64,
+    #[arg(long)]
+    enable_tls: bool,
+    #[arg(long)]
+    tls_cert_path: Option<String>,
+    #[arg(long)]
+    tls_key_path: Option<String>,
+    #[arg(long)]
+    tls_ca_path: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut builder = env_logger::Builder::from_default_env();
+    builder
+        .format_timestamp(None)
+        .filter(None, log::LevelFilter::Debug);
+    builder.init();
+
+    let args = Args::parse();
+    let tls_material = if args.enable_tls {
+        Some(sealfs::rpc::tls::TlsMaterial {
+            cert_path: args.tls_cert_path.unwrap_or_default(),
+            key_path: args.tls_key_path.unwrap_or_default(),
+            ca_path: args.tls_ca_path,
+        })
+    } else {
+        None
+    };
+
+    info!("Start Manager on {}", args.manager_address);
+    manager::run(
+        args.manager_address,
+        Duration::from_secs(args.reap_interval_secs),
+        tls_material,
+    )
+    .await
+}
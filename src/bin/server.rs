@@ -2,15 +2,18 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use anyhow::Context;
 use clap::Parser;
-use log::{info, warn};
+use log::{error, info, warn};
+use sealfs::common::backoff::Backoff;
 use sealfs::common::serialization::OperationType;
-use sealfs::manager::manager_service::SendHeartRequest;
+use sealfs::config::{self, Overrides};
+use sealfs::manager::manager_service::{self, SendHeartRequest, ServerState};
 use sealfs::rpc::client::ClientAsync;
 use sealfs::server;
-use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
-use std::fs;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 use tokio::time;
 use tokio::time::MissedTickBehavior;
 
@@ -33,88 +36,79 @@ struct Args {
     storage_path: Option<String>,
     #[arg(long)]
     heartbeat: Option<bool>,
-    /// The path of the configuration file
+    /// The path of a config file to merge in below CLI flags and environment variables
     #[arg(long)]
     config_file: Option<String>,
-    /// To use customized configuration or not. If this flag is used, please provide a config file through --config_file <path>
+    /// Serve and dial other servers over TLS instead of plaintext TCP
     #[arg(long)]
-    use_config_file: bool,
+    enable_tls: Option<bool>,
+    #[arg(long)]
+    tls_cert_path: Option<String>,
+    #[arg(long)]
+    tls_key_path: Option<String>,
+    #[arg(long)]
+    tls_ca_path: Option<String>,
+    /// How often to report a heartbeat to the manager, in seconds
+    #[arg(long)]
+    heartbeat_interval_secs: Option<u64>,
+    /// Largest delay between manager reconnection attempts, in seconds
+    #[arg(long)]
+    backoff_max_secs: Option<u64>,
+    /// Ceiling on cumulative reconnection backoff before giving up, in seconds
+    #[arg(long)]
+    backoff_max_elapsed_secs: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Properties {
-    manager_address: String,
-    server_address: String,
-    all_servers_address: Vec<String>,
-    lifetime: String,
-    database_path: String,
-    storage_path: String,
-    heartbeat: bool,
+impl From<Args> for Overrides {
+    fn from(args: Args) -> Self {
+        Overrides {
+            manager_address: args.manager_address,
+            server_address: args.server_address,
+            all_servers_address: args.all_servers_address,
+            lifetime: args.lifetime,
+            database_path: args.database_path,
+            storage_path: args.storage_path,
+            heartbeat: args.heartbeat,
+            enable_tls: args.enable_tls,
+            tls_cert_path: args.tls_cert_path,
+            tls_key_path: args.tls_key_path,
+            tls_ca_path: args.tls_ca_path,
+            heartbeat_interval_secs: args.heartbeat_interval_secs,
+            backoff_max_secs: args.backoff_max_secs,
+            backoff_max_elapsed_secs: args.backoff_max_elapsed_secs,
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
+async fn main() -> anyhow::Result<()> {
     let mut builder = env_logger::Builder::from_default_env();
     builder
         .format_timestamp(None)
         .filter(None, log::LevelFilter::Debug);
     builder.init();
 
-    // read from default configuration.
-    let default_yaml_str = include_str!("../../examples/server.yaml");
-    let default_properties: Properties =
-        serde_yaml::from_str(default_yaml_str).expect("server.yaml read failed!");
-
-    // read from command line.
-    let args: Args = Args::parse();
-    // if the user provides the config file, parse it and use the arguments from the config file.
-    let properties: Properties = match args.use_config_file {
-        true => {
-            // read from default configuration.
-            match args.config_file {
-                Some(c) => {
-                    // read from user-provided config file
-                    let yaml_str = fs::read_to_string(c).expect("Couldn't read from file. The file is either missing or you don't have enough permissions!");
-                    let result: Properties =
-                        serde_yaml::from_str(&yaml_str).expect("server.yaml read failed!");
-                    result
-                }
-                _ => {
-                    warn!(
-                        "No custom configuration provided, fallback to the default configuration."
-                    );
-                    default_properties
-                }
-            }
-        }
-        false => Properties {
-            manager_address: args
-                .manager_address
-                .unwrap_or(default_properties.manager_address),
-            server_address: args
-                .server_address
-                .unwrap_or(default_properties.server_address),
-            all_servers_address: args
-                .all_servers_address
-                .unwrap_or(default_properties.all_servers_address),
-
-            lifetime: args.lifetime.unwrap_or(default_properties.lifetime),
-            database_path: args
-                .database_path
-                .unwrap_or(default_properties.database_path),
-            storage_path: args.storage_path.unwrap_or(default_properties.storage_path),
-            heartbeat: args.heartbeat.unwrap_or(default_properties.heartbeat),
-        },
-    };
-
-    let manager_address = properties.manager_address;
-    let _server_address = properties.server_address.clone();
+    let args = Args::parse();
+    let config_file = args.config_file.clone();
+    let overrides: Overrides = args.into();
+    let properties =
+        config::load(&overrides, config_file.as_deref()).context("failed to load configuration")?;
+
+    let tls_material = properties.tls_material();
+    let manager_address = properties.manager_address.clone();
     //connect to manager
 
+    // Seeded from the static config value, then kept fresh from the
+    // manager's `ListServers` view once heartbeating is on, so routing
+    // around dead peers doesn't depend on an operator editing the config.
+    let all_servers_address = Arc::new(RwLock::new(properties.all_servers_address.clone()));
+
     if properties.heartbeat {
         info!("Connect To Manager.");
-        let client = ClientAsync::new();
-        client.add_connection(&manager_address).await;
+        let client = match tls_material.clone() {
+            Some(material) => ClientAsync::new_with_tls(material),
+            None => ClientAsync::new(),
+        };
 
         //begin scheduled task
         tokio::spawn(begin_heartbeat_report(
@@ -122,6 +116,10 @@ async fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
             manager_address,
             properties.server_address.clone(),
             properties.lifetime.clone(),
+            properties.heartbeat_interval_secs,
+            properties.backoff_max_secs,
+            properties.backoff_max_elapsed_secs,
+            all_servers_address.clone(),
         ));
     }
 
@@ -137,7 +135,8 @@ async fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
         properties.database_path.clone(),
         properties.storage_path.clone(),
         properties.server_address.clone(),
-        properties.all_servers_address.clone(),
+        all_servers_address,
+        tls_material,
     )
     .await?;
     // Server::builder()
@@ -149,15 +148,53 @@ async fn main() -> anyhow::Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+const BACKOFF_BASE: time::Duration = time::Duration::from_millis(100);
+
+/// Reports a heartbeat to the manager on a fixed interval. A failed send
+/// (including a dropped connection) no longer aborts the process: it
+/// reconnects with capped exponential backoff and jitter, and resumes the
+/// normal interval as soon as a heartbeat succeeds again. Gives up (and
+/// stops heartbeating, though the server keeps serving requests) once
+/// `backoff_max_elapsed_secs` of cumulative retrying has passed without a
+/// success.
+#[allow(clippy::too_many_arguments)]
 async fn begin_heartbeat_report(
     client: ClientAsync,
     manager_address: String,
     server_address: String,
     lifetime: String,
+    heartbeat_interval_secs: u64,
+    backoff_max_secs: u64,
+    backoff_max_elapsed_secs: u64,
+    all_servers_address: Arc<RwLock<Vec<String>>>,
 ) {
-    let mut interval = time::interval(time::Duration::from_secs(5));
+    let mut interval = time::interval(time::Duration::from_secs(heartbeat_interval_secs));
     interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut backoff = Backoff::new(
+        BACKOFF_BASE,
+        time::Duration::from_secs(backoff_max_secs),
+        time::Duration::from_secs(backoff_max_elapsed_secs),
+    );
+    let mut connected = false;
+
     loop {
+        if !connected {
+            match client.add_connection(&manager_address).await {
+                Ok(()) => connected = true,
+                Err(e) => {
+                    let Some(delay) = backoff.next_delay() else {
+                        error!(
+                            "giving up connecting to manager after {backoff_max_elapsed_secs}s of retrying: {e:?}"
+                        );
+                        return;
+                    };
+                    warn!("failed to connect to manager, retrying in {delay:?}: {e:?}");
+                    time::sleep(delay).await;
+                    continue;
+                }
+            }
+        }
+
         let request = SendHeartRequest {
             address: server_address.clone(),
             flags: SERVER_FLAG,
@@ -167,27 +204,62 @@ async fn begin_heartbeat_report(
         let mut rsp_flags = 0u32;
         let mut recv_meta_data_length = 0usize;
         let mut recv_data_length = 0usize;
-        {
-            let result = client
-                .call_remote(
-                    &manager_address,
-                    OperationType::SendHeart.into(),
-                    0,
-                    &server_address,
-                    &bincode::serialize(&request).unwrap(),
-                    &[],
-                    &mut status,
-                    &mut rsp_flags,
-                    &mut recv_meta_data_length,
-                    &mut recv_data_length,
-                    &mut [],
-                    &mut [],
-                )
-                .await;
-            if result.is_err() {
-                panic!("send heartbeat error. {:?}", result);
+        let result = client
+            .call_remote(
+                &manager_address,
+                OperationType::SendHeart.into(),
+                0,
+                &server_address,
+                &bincode::serialize(&request).unwrap(),
+                &[],
+                &mut status,
+                &mut rsp_flags,
+                &mut recv_meta_data_length,
+                &mut recv_data_length,
+                &mut [],
+                &mut [],
+            )
+            .await;
+
+        match result {
+            Ok(()) => {
+                backoff.reset();
+                refresh_all_servers_address(&client, &manager_address, &all_servers_address).await;
+                interval.tick().await;
+            }
+            Err(e) => {
+                connected = false;
+                let Some(delay) = backoff.next_delay() else {
+                    error!(
+                        "giving up on manager heartbeat after {backoff_max_elapsed_secs}s of retrying: {e:?}"
+                    );
+                    return;
+                };
+                warn!("send heartbeat error, reconnecting in {delay:?}: {e:?}");
+                time::sleep(delay).await;
             }
         }
-        interval.tick().await;
+    }
+}
+
+/// Replaces `all_servers_address` with the manager's current view, keeping
+/// only servers it still considers alive. Leaves the previous value in
+/// place on failure rather than clearing it, so a transient `ListServers`
+/// error doesn't blank out routing.
+async fn refresh_all_servers_address(
+    client: &ClientAsync,
+    manager_address: &str,
+    all_servers_address: &RwLock<Vec<String>>,
+) {
+    match manager_service::list_servers(client, manager_address).await {
+        Ok(servers) => {
+            let alive: Vec<String> = servers
+                .into_iter()
+                .filter(|server| server.state == ServerState::Alive)
+                .map(|server| server.address)
+                .collect();
+            *all_servers_address.write().await = alive;
+        }
+        Err(e) => warn!("failed to refresh server list from manager: {e:?}"),
     }
 }
@@ -0,0 +1,122 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Capped exponential backoff with jitter, used by anything that needs to
+//! retry a flaky connection (e.g. a server reconnecting to the manager)
+//! without hammering the peer or falling into lockstep with other retriers.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Tracks retry state for one logical connection attempt loop. Create one,
+/// call `next_delay` after each failure, and `reset` after a success.
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    max_elapsed: Duration,
+    attempt: u32,
+    elapsed: Duration,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration, max_elapsed: Duration) -> Self {
+        Self {
+            base,
+            max,
+            max_elapsed,
+            attempt: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// The delay to sleep before the next retry: `min(max, base * 2^attempt)`
+    /// with jitter in `[0.5, 1.5]`, or `None` once the cumulative elapsed
+    /// retry time has already reached `max_elapsed`, telling the caller to
+    /// give up instead of scheduling another attempt.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.elapsed >= self.max_elapsed {
+            return None;
+        }
+
+        let exp = self.base.saturating_mul(1 << self.attempt.min(31));
+        let capped = exp.min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        self.elapsed = self.elapsed.saturating_add(capped);
+
+        let jitter_factor = rand::thread_rng().gen_range(0.5..1.5);
+        Some(capped.mul_f64(jitter_factor))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.elapsed = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_stays_within_jittered_bounds_of_the_cap() {
+        // base == max, so every attempt is already at the cap and the only
+        // variance left to check is the jitter factor.
+        let mut backoff = Backoff::new(
+            Duration::from_secs(10),
+            Duration::from_secs(10),
+            Duration::from_secs(3600),
+        );
+        for _ in 0..10 {
+            let delay = backoff.next_delay().expect("well under max_elapsed");
+            assert!(delay >= Duration::from_secs(5), "delay was {delay:?}");
+            assert!(delay <= Duration::from_secs(15), "delay was {delay:?}");
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_before_hitting_the_cap() {
+        let mut backoff = Backoff::new(
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            Duration::from_secs(3600),
+        );
+        let first = backoff.next_delay().unwrap();
+        let second = backoff.next_delay().unwrap();
+        // With jitter in [0.5, 1.5], the first delay (base 100ms) is at most
+        // 150ms, and the second (base doubled to 200ms) is at least 100ms.
+        assert!(first <= Duration::from_millis(150));
+        assert!(second >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn reset_forgets_prior_attempts() {
+        let mut backoff = Backoff::new(
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            Duration::from_secs(3600),
+        );
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+        backoff.reset();
+        let delay = backoff.next_delay().unwrap();
+        assert!(delay <= Duration::from_millis(150), "delay was {delay:?}");
+    }
+
+    #[test]
+    fn gives_up_once_max_elapsed_is_reached() {
+        let mut backoff = Backoff::new(
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+        );
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(
+            backoff.next_delay().is_none(),
+            "should give up once cumulative elapsed reaches max_elapsed"
+        );
+    }
+}
@@ -0,0 +1,6 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod backoff;
+pub mod serialization;
@@ -0,0 +1,189 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wire-level types and framing shared between the RPC client and server:
+//! the set of operations a request can carry, and the length-prefixed
+//! header format used to encode requests/responses on the byte stream.
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Every request sent over the RPC transport is tagged with one of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum OperationType {
+    Unknown = 0,
+    SendHeart = 1,
+    ListServers = 2,
+}
+
+impl From<OperationType> for u32 {
+    fn from(op: OperationType) -> Self {
+        op as u32
+    }
+}
+
+impl From<u32> for OperationType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => OperationType::SendHeart,
+            2 => OperationType::ListServers,
+            _ => OperationType::Unknown,
+        }
+    }
+}
+
+/// `operation_type`, `flags`, `path_length`, `meta_data_length`, `data_length`.
+pub const REQUEST_HEADER_LEN: usize = 4 * 5;
+/// `status`, `rsp_flags`, `meta_data_length`, `data_length`.
+pub const RESPONSE_HEADER_LEN: usize = 4 * 4;
+
+/// Bit of `flags` that marks a request as using the streaming dispatch path
+/// rather than the one-shot buffered path. Kept as a single documented
+/// constant so client and server agree on which bit it is.
+pub const STREAM_FLAG: u32 = 1 << 31;
+
+pub struct RequestHeader {
+    pub operation_type: u32,
+    pub flags: u32,
+    pub path_len: u32,
+    pub meta_data_len: u32,
+    pub data_len: u32,
+}
+
+pub struct ResponseHeader {
+    pub status: i32,
+    pub rsp_flags: u32,
+    pub meta_data_len: u32,
+    pub data_len: u32,
+}
+
+/// Writes a request header, then `path`, `metadata` and `data` back to
+/// back. Generic over the stream so the same framing works whether the
+/// caller is holding a raw `TcpStream` or a `tokio-rustls` TLS stream.
+pub async fn write_request<S>(
+    stream: &mut S,
+    operation_type: u32,
+    flags: u32,
+    path: &[u8],
+    metadata: &[u8],
+    data: &[u8],
+) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_u32_le(operation_type).await?;
+    stream.write_u32_le(flags).await?;
+    stream.write_u32_le(path.len() as u32).await?;
+    stream.write_u32_le(metadata.len() as u32).await?;
+    stream.write_u32_le(data.len() as u32).await?;
+    stream.write_all(path).await?;
+    stream.write_all(metadata).await?;
+    stream.write_all(data).await?;
+    stream.flush().await
+}
+
+pub async fn read_request_header<S>(stream: &mut S) -> std::io::Result<RequestHeader>
+where
+    S: AsyncRead + Unpin,
+{
+    Ok(RequestHeader {
+        operation_type: stream.read_u32_le().await?,
+        flags: stream.read_u32_le().await?,
+        path_len: stream.read_u32_le().await?,
+        meta_data_len: stream.read_u32_le().await?,
+        data_len: stream.read_u32_le().await?,
+    })
+}
+
+/// Writes a response header, then `metadata` and `data` back to back.
+pub async fn write_response<S>(
+    stream: &mut S,
+    status: i32,
+    rsp_flags: u32,
+    metadata: &[u8],
+    data: &[u8],
+) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_i32_le(status).await?;
+    stream.write_u32_le(rsp_flags).await?;
+    stream.write_u32_le(metadata.len() as u32).await?;
+    stream.write_u32_le(data.len() as u32).await?;
+    stream.write_all(metadata).await?;
+    stream.write_all(data).await?;
+    stream.flush().await
+}
+
+pub async fn read_response_header<S>(stream: &mut S) -> std::io::Result<ResponseHeader>
+where
+    S: AsyncRead + Unpin,
+{
+    Ok(ResponseHeader {
+        status: stream.read_i32_le().await?,
+        rsp_flags: stream.read_u32_le().await?,
+        meta_data_len: stream.read_u32_le().await?,
+        data_len: stream.read_u32_le().await?,
+    })
+}
+
+/// Writes one chunk of a streamed request/response body: a `u32` length
+/// prefix, offset by one so `0` is free to mean end-of-stream, followed by
+/// the chunk bytes. This lets a legitimate zero-length chunk round-trip
+/// instead of being mistaken for `write_stream_end`'s marker.
+pub async fn write_chunk<S>(stream: &mut S, chunk: &[u8]) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_u32_le(chunk.len() as u32 + 1).await?;
+    stream.write_all(chunk).await?;
+    stream.flush().await
+}
+
+pub async fn write_stream_end<S>(stream: &mut S) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_u32_le(0).await?;
+    stream.flush().await
+}
+
+/// Reads one chunk written by `write_chunk`, or `None` once the body's
+/// `write_stream_end` marker is reached.
+pub async fn read_chunk<S>(stream: &mut S) -> std::io::Result<Option<Vec<u8>>>
+where
+    S: AsyncRead + Unpin,
+{
+    let len_code = stream.read_u32_le().await?;
+    if len_code == 0 {
+        return Ok(None);
+    }
+    let mut buf = vec![0u8; (len_code - 1) as usize];
+    stream.read_exact(&mut buf).await?;
+    Ok(Some(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn chunks_round_trip_and_a_zero_length_chunk_is_not_mistaken_for_the_end_marker() {
+        let (mut writer, mut reader) = tokio::io::duplex(1024);
+
+        let chunks: Vec<Vec<u8>> = vec![b"hello".to_vec(), vec![], b"world".to_vec()];
+        for chunk in &chunks {
+            write_chunk(&mut writer, chunk).await.unwrap();
+        }
+        write_stream_end(&mut writer).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Some(chunk) = read_chunk(&mut reader).await.unwrap() {
+            received.push(chunk);
+        }
+
+        assert_eq!(received, chunks);
+    }
+}
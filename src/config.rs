@@ -0,0 +1,482 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Layered, validated server configuration. Sources are merged in
+//! precedence order: environment variables, then CLI flags, then the
+//! user's config file, then the embedded `examples/server.yaml` defaults.
+//! The merged result is validated before `server::run` ever sees it, so a
+//! bad value is reported as a precise [`ConfigError`] instead of an opaque
+//! `panic!`.
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rpc::tls::TlsMaterial;
+
+const DEFAULT_YAML: &str = include_str!("../examples/server.yaml");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Properties {
+    pub manager_address: String,
+    pub server_address: String,
+    pub all_servers_address: Vec<String>,
+    pub lifetime: String,
+    pub database_path: String,
+    pub storage_path: String,
+    pub heartbeat: bool,
+    pub enable_tls: bool,
+    pub tls_cert_path: String,
+    pub tls_key_path: String,
+    pub tls_ca_path: String,
+    pub heartbeat_interval_secs: u64,
+    pub backoff_max_secs: u64,
+    pub backoff_max_elapsed_secs: u64,
+}
+
+impl Properties {
+    /// `None` when TLS is disabled, otherwise the cert/key/CA paths ready
+    /// to hand to the RPC client or server.
+    pub fn tls_material(&self) -> Option<TlsMaterial> {
+        if !self.enable_tls {
+            return None;
+        }
+        Some(TlsMaterial {
+            cert_path: self.tls_cert_path.clone(),
+            key_path: self.tls_key_path.clone(),
+            ca_path: if self.tls_ca_path.is_empty() {
+                None
+            } else {
+                Some(self.tls_ca_path.clone())
+            },
+        })
+    }
+}
+
+/// CLI-flag overrides: one layer below environment variables, one layer
+/// above the user's config file. Every field is optional so an unset flag
+/// falls through to the next layer instead of clobbering it.
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    pub manager_address: Option<String>,
+    pub server_address: Option<String>,
+    pub all_servers_address: Option<Vec<String>>,
+    pub lifetime: Option<String>,
+    pub database_path: Option<String>,
+    pub storage_path: Option<String>,
+    pub heartbeat: Option<bool>,
+    pub enable_tls: Option<bool>,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub tls_ca_path: Option<String>,
+    pub heartbeat_interval_secs: Option<u64>,
+    pub backoff_max_secs: Option<u64>,
+    pub backoff_max_elapsed_secs: Option<u64>,
+}
+
+/// Which layer a merged field's final value came from, so a validation
+/// error can point at the source that needs fixing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Env,
+    Cli,
+    ConfigFile,
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Source::Env => "an environment variable",
+            Source::Cli => "a command-line flag",
+            Source::ConfigFile => "the config file",
+            Source::Default => "the embedded default config",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("could not read config file '{path}': {source}")]
+    ReadConfigFile {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("could not parse '{path}' as YAML: {source}")]
+    ParseConfigFile {
+        path: String,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    #[error("could not parse the embedded default config: {0}")]
+    ParseDefaults(#[source] serde_yaml::Error),
+
+    #[error("invalid '{field}' (from {layer}): {reason}")]
+    InvalidField {
+        field: &'static str,
+        layer: Source,
+        reason: String,
+    },
+}
+
+/// Merges `overrides` and an optional user `config_file` on top of the
+/// embedded defaults, lets environment variables override all of that,
+/// and validates the result.
+pub fn load(overrides: &Overrides, config_file: Option<&str>) -> Result<Properties, ConfigError> {
+    let defaults: Properties =
+        serde_yaml::from_str(DEFAULT_YAML).map_err(ConfigError::ParseDefaults)?;
+
+    let file: Option<Properties> = config_file
+        .map(|path| {
+            let contents =
+                std::fs::read_to_string(path).map_err(|source| ConfigError::ReadConfigFile {
+                    path: path.to_string(),
+                    source,
+                })?;
+            serde_yaml::from_str(&contents).map_err(|source| ConfigError::ParseConfigFile {
+                path: path.to_string(),
+                source,
+            })
+        })
+        .transpose()?;
+
+    let (manager_address, manager_address_src) = layer(
+        env_string("SEALFS_MANAGER_ADDRESS"),
+        overrides.manager_address.clone(),
+        file.as_ref().map(|f| f.manager_address.clone()),
+        defaults.manager_address.clone(),
+    );
+    let (server_address, server_address_src) = layer(
+        env_string("SEALFS_SERVER_ADDRESS"),
+        overrides.server_address.clone(),
+        file.as_ref().map(|f| f.server_address.clone()),
+        defaults.server_address.clone(),
+    );
+    let (all_servers_address, _) = layer(
+        env_list("SEALFS_ALL_SERVERS_ADDRESS"),
+        overrides.all_servers_address.clone(),
+        file.as_ref().map(|f| f.all_servers_address.clone()),
+        defaults.all_servers_address.clone(),
+    );
+    let (lifetime, lifetime_src) = layer(
+        env_string("SEALFS_LIFETIME"),
+        overrides.lifetime.clone(),
+        file.as_ref().map(|f| f.lifetime.clone()),
+        defaults.lifetime.clone(),
+    );
+    let (database_path, database_path_src) = layer(
+        env_string("SEALFS_DATABASE_PATH"),
+        overrides.database_path.clone(),
+        file.as_ref().map(|f| f.database_path.clone()),
+        defaults.database_path.clone(),
+    );
+    let (storage_path, storage_path_src) = layer(
+        env_string("SEALFS_STORAGE_PATH"),
+        overrides.storage_path.clone(),
+        file.as_ref().map(|f| f.storage_path.clone()),
+        defaults.storage_path.clone(),
+    );
+    let (heartbeat, _) = layer(
+        env_bool("SEALFS_HEARTBEAT"),
+        overrides.heartbeat,
+        file.as_ref().map(|f| f.heartbeat),
+        defaults.heartbeat,
+    );
+    let (enable_tls, _) = layer(
+        env_bool("SEALFS_ENABLE_TLS"),
+        overrides.enable_tls,
+        file.as_ref().map(|f| f.enable_tls),
+        defaults.enable_tls,
+    );
+    let (tls_cert_path, _) = layer(
+        env_string("SEALFS_TLS_CERT_PATH"),
+        overrides.tls_cert_path.clone(),
+        file.as_ref().map(|f| f.tls_cert_path.clone()),
+        defaults.tls_cert_path.clone(),
+    );
+    let (tls_key_path, _) = layer(
+        env_string("SEALFS_TLS_KEY_PATH"),
+        overrides.tls_key_path.clone(),
+        file.as_ref().map(|f| f.tls_key_path.clone()),
+        defaults.tls_key_path.clone(),
+    );
+    let (tls_ca_path, _) = layer(
+        env_string("SEALFS_TLS_CA_PATH"),
+        overrides.tls_ca_path.clone(),
+        file.as_ref().map(|f| f.tls_ca_path.clone()),
+        defaults.tls_ca_path.clone(),
+    );
+    let (heartbeat_interval_secs, _) = layer(
+        env_u64("SEALFS_HEARTBEAT_INTERVAL_SECS"),
+        overrides.heartbeat_interval_secs,
+        file.as_ref().map(|f| f.heartbeat_interval_secs),
+        defaults.heartbeat_interval_secs,
+    );
+    let (backoff_max_secs, _) = layer(
+        env_u64("SEALFS_BACKOFF_MAX_SECS"),
+        overrides.backoff_max_secs,
+        file.as_ref().map(|f| f.backoff_max_secs),
+        defaults.backoff_max_secs,
+    );
+    let (backoff_max_elapsed_secs, _) = layer(
+        env_u64("SEALFS_BACKOFF_MAX_ELAPSED_SECS"),
+        overrides.backoff_max_elapsed_secs,
+        file.as_ref().map(|f| f.backoff_max_elapsed_secs),
+        defaults.backoff_max_elapsed_secs,
+    );
+
+    if manager_address.trim().is_empty() {
+        return Err(ConfigError::InvalidField {
+            field: "manager_address",
+            layer: manager_address_src,
+            reason: "must not be empty".to_string(),
+        });
+    }
+    manager_address
+        .parse::<SocketAddr>()
+        .map_err(|e| ConfigError::InvalidField {
+            field: "manager_address",
+            layer: manager_address_src,
+            reason: format!("not a valid socket address: {e}"),
+        })?;
+
+    if server_address.trim().is_empty() {
+        return Err(ConfigError::InvalidField {
+            field: "server_address",
+            layer: server_address_src,
+            reason: "must not be empty".to_string(),
+        });
+    }
+    server_address
+        .parse::<SocketAddr>()
+        .map_err(|e| ConfigError::InvalidField {
+            field: "server_address",
+            layer: server_address_src,
+            reason: format!("not a valid socket address: {e}"),
+        })?;
+
+    humantime::parse_duration(&lifetime).map_err(|e| ConfigError::InvalidField {
+        field: "lifetime",
+        layer: lifetime_src,
+        reason: format!("not a valid duration: {e}"),
+    })?;
+
+    check_writable(&database_path).map_err(|e| ConfigError::InvalidField {
+        field: "database_path",
+        layer: database_path_src,
+        reason: format!("not writable: {e}"),
+    })?;
+    check_writable(&storage_path).map_err(|e| ConfigError::InvalidField {
+        field: "storage_path",
+        layer: storage_path_src,
+        reason: format!("not writable: {e}"),
+    })?;
+
+    Ok(Properties {
+        manager_address,
+        server_address,
+        all_servers_address,
+        lifetime,
+        database_path,
+        storage_path,
+        heartbeat,
+        enable_tls,
+        tls_cert_path,
+        tls_key_path,
+        tls_ca_path,
+        heartbeat_interval_secs,
+        backoff_max_secs,
+        backoff_max_elapsed_secs,
+    })
+}
+
+/// Picks the highest-precedence `Some` value and reports which layer it
+/// came from, falling back to `default` (always present) otherwise.
+fn layer<T>(
+    env_value: Option<T>,
+    cli_value: Option<T>,
+    file_value: Option<T>,
+    default_value: T,
+) -> (T, Source) {
+    if let Some(value) = env_value {
+        (value, Source::Env)
+    } else if let Some(value) = cli_value {
+        (value, Source::Cli)
+    } else if let Some(value) = file_value {
+        (value, Source::ConfigFile)
+    } else {
+        (default_value, Source::Default)
+    }
+}
+
+fn env_string(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+fn env_bool(name: &str) -> Option<bool> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    std::env::var(name)
+        .ok()
+        .map(|v| v.split(',').map(|s| s.trim().to_string()).collect())
+}
+
+/// A directory is "writable" if it exists or can be created, and a file
+/// can be created and removed inside it.
+fn check_writable(path: &str) -> std::io::Result<()> {
+    std::fs::create_dir_all(path)?;
+    let probe = std::path::Path::new(path).join(".sealfs-write-check");
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `load` always checks these env vars first; tests clear them so a
+    /// developer's shell environment can't make a run flaky.
+    fn clear_env() {
+        for var in [
+            "SEALFS_MANAGER_ADDRESS",
+            "SEALFS_SERVER_ADDRESS",
+            "SEALFS_ALL_SERVERS_ADDRESS",
+            "SEALFS_LIFETIME",
+            "SEALFS_DATABASE_PATH",
+            "SEALFS_STORAGE_PATH",
+            "SEALFS_HEARTBEAT",
+            "SEALFS_ENABLE_TLS",
+            "SEALFS_TLS_CERT_PATH",
+            "SEALFS_TLS_KEY_PATH",
+            "SEALFS_TLS_CA_PATH",
+            "SEALFS_HEARTBEAT_INTERVAL_SECS",
+            "SEALFS_BACKOFF_MAX_SECS",
+            "SEALFS_BACKOFF_MAX_ELAPSED_SECS",
+        ] {
+            std::env::remove_var(var);
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("sealfs-config-test-{}-{name}", std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn writable_overrides() -> Overrides {
+        Overrides {
+            database_path: Some(temp_path("database")),
+            storage_path: Some(temp_path("storage")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn layer_picks_highest_precedence_value() {
+        assert_eq!(layer(Some(1), Some(2), Some(3), 4), (1, Source::Env));
+        assert_eq!(layer(None, Some(2), Some(3), 4), (2, Source::Cli));
+        assert_eq!(layer(None, None, Some(3), 4), (3, Source::ConfigFile));
+        assert_eq!(layer(None::<i32>, None, None, 4), (4, Source::Default));
+    }
+
+    #[test]
+    fn load_falls_back_to_embedded_defaults() {
+        clear_env();
+        let properties = load(&writable_overrides(), None).expect("embedded defaults validate");
+        assert_eq!(properties.manager_address, "127.0.0.1:9001");
+        assert_eq!(properties.heartbeat_interval_secs, 5);
+    }
+
+    #[test]
+    fn config_file_beats_defaults_but_cli_beats_the_config_file() {
+        clear_env();
+        let config_path = temp_path("precedence.yaml");
+        std::fs::write(
+            &config_path,
+            "manager_address: \"127.0.0.1:5000\"\n\
+             server_address: \"127.0.0.1:6000\"\n\
+             all_servers_address:\n  - \"127.0.0.1:6000\"\n\
+             lifetime: \"20s\"\n\
+             database_path: \"/tmp\"\n\
+             storage_path: \"/tmp\"\n\
+             heartbeat: true\n\
+             enable_tls: false\n\
+             tls_cert_path: \"\"\n\
+             tls_key_path: \"\"\n\
+             tls_ca_path: \"\"\n\
+             heartbeat_interval_secs: 5\n\
+             backoff_max_secs: 30\n\
+             backoff_max_elapsed_secs: 300\n",
+        )
+        .unwrap();
+
+        let overrides = Overrides {
+            manager_address: Some("127.0.0.1:7000".to_string()),
+            ..writable_overrides()
+        };
+        let properties = load(&overrides, Some(&config_path)).expect("config file validates");
+
+        // CLI override wins over the config file's value.
+        assert_eq!(properties.manager_address, "127.0.0.1:7000");
+        // The config file's value wins over the embedded default
+        // ("127.0.0.1:8001") since there's no CLI override for it.
+        assert_eq!(properties.server_address, "127.0.0.1:6000");
+
+        std::fs::remove_file(&config_path).ok();
+    }
+
+    #[test]
+    fn rejects_an_unparseable_manager_address() {
+        clear_env();
+        let overrides = Overrides {
+            manager_address: Some("not-an-address".to_string()),
+            ..writable_overrides()
+        };
+        let err = load(&overrides, None).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidField { field: "manager_address", .. }
+        ));
+    }
+
+    #[test]
+    fn rejects_an_empty_server_address() {
+        clear_env();
+        let overrides = Overrides {
+            server_address: Some(String::new()),
+            ..writable_overrides()
+        };
+        let err = load(&overrides, None).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidField { field: "server_address", .. }
+        ));
+    }
+
+    #[test]
+    fn rejects_an_unparseable_lifetime() {
+        clear_env();
+        let overrides = Overrides {
+            lifetime: Some("not-a-duration".to_string()),
+            ..writable_overrides()
+        };
+        let err = load(&overrides, None).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidField { field: "lifetime", .. }
+        ));
+    }
+}
@@ -0,0 +1,9 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod common;
+pub mod config;
+pub mod manager;
+pub mod rpc;
+pub mod server;
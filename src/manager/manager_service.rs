@@ -0,0 +1,102 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request/response payloads exchanged between a server and the manager it
+//! reports in to. These are carried as the `data` portion of an RPC
+//! request tagged with the matching `OperationType`, bincode-encoded.
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::serialization::OperationType;
+use crate::rpc::client::ClientAsync;
+
+/// Sent by a server on every heartbeat tick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendHeartRequest {
+    pub address: String,
+    pub flags: u32,
+    pub lifetime: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendHeartResponse {
+    pub status: i32,
+}
+
+/// Whether a `ListServers` query has an empty body today, but is still a
+/// named type so a future filter (e.g. by flag) doesn't change the wire
+/// contract.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListServersRequest {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListServersResponse {
+    pub servers: Vec<ServerInfo>,
+}
+
+/// A server the manager has heard from, as reported by `ListServers`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub address: String,
+    pub flags: u32,
+    pub last_seen_unix_secs: u64,
+    pub state: ServerState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerState {
+    Alive,
+    Dead,
+}
+
+/// The read buffer `list_servers` hands `call_remote`. A response larger
+/// than this is reported as a distinct `anyhow` error rather than being
+/// silently truncated and fed to `bincode` as corrupted bytes.
+const LIST_SERVERS_RECV_BUFFER_LEN: usize = 64 * 1024;
+
+/// Queries `manager_address` for every server it currently tracks,
+/// including ones it has declared dead but not yet dropped. Lets a caller refresh
+/// `all_servers_address` from a live, authoritative source instead of a
+/// static config list.
+///
+/// The response is capped at `LIST_SERVERS_RECV_BUFFER_LEN`; a deployment
+/// with more servers than fit in it gets a clear error instead of a
+/// `bincode` failure on truncated bytes, since `call_remote`'s single-shot
+/// exchange has already discarded whatever didn't fit on the wire by the
+/// time that would be detected.
+pub async fn list_servers(
+    client: &ClientAsync,
+    manager_address: &str,
+) -> anyhow::Result<Vec<ServerInfo>> {
+    let mut status = 0i32;
+    let mut rsp_flags = 0u32;
+    let mut recv_meta_data_length = 0usize;
+    let mut recv_data_length = 0usize;
+    let mut recv_data = vec![0u8; LIST_SERVERS_RECV_BUFFER_LEN];
+    client
+        .call_remote(
+            manager_address,
+            OperationType::ListServers.into(),
+            0,
+            "",
+            &[],
+            &[],
+            &mut status,
+            &mut rsp_flags,
+            &mut recv_meta_data_length,
+            &mut recv_data_length,
+            &mut [],
+            &mut recv_data,
+        )
+        .await?;
+    if recv_data_length > recv_data.len() {
+        anyhow::bail!(
+            "ListServers response ({recv_data_length} bytes) exceeds the {} byte read buffer",
+            recv_data.len()
+        );
+    }
+    recv_data.truncate(recv_data_length);
+    let response: ListServersResponse = bincode::deserialize(&recv_data)?;
+    Ok(response.servers)
+}
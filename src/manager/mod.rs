@@ -0,0 +1,92 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The manager: accepts `SendHeart` reports from servers and answers
+//! `ListServers` queries from clients that want to route around servers
+//! it has declared dead.
+
+pub mod manager_service;
+pub mod registry;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::warn;
+
+use crate::common::serialization::OperationType;
+use crate::manager::manager_service::{
+    ListServersResponse, SendHeartRequest, SendHeartResponse,
+};
+use crate::manager::registry::Registry;
+use crate::rpc::server::{Handler, Server};
+use crate::rpc::tls::TlsMaterial;
+
+struct ManagerHandler {
+    registry: Arc<Registry>,
+}
+
+#[async_trait]
+impl Handler for ManagerHandler {
+    async fn dispatch(
+        &self,
+        operation_type: u32,
+        _flags: u32,
+        _path: Vec<u8>,
+        data: Vec<u8>,
+        _metadata: Vec<u8>,
+    ) -> anyhow::Result<(i32, u32, Vec<u8>, Vec<u8>)> {
+        match OperationType::from(operation_type) {
+            OperationType::SendHeart => {
+                let request: SendHeartRequest = bincode::deserialize(&data)?;
+                let lifetime = humantime::parse_duration(&request.lifetime).unwrap_or_else(|e| {
+                    warn!(
+                        "server {} sent an unparseable lifetime '{}' ({e}), defaulting to 20s",
+                        request.address, request.lifetime
+                    );
+                    Duration::from_secs(20)
+                });
+                self.registry
+                    .record_heartbeat(request.address, request.flags, lifetime)
+                    .await;
+                let response = SendHeartResponse { status: 0 };
+                Ok((0, 0, vec![], bincode::serialize(&response)?))
+            }
+            OperationType::ListServers => {
+                let servers = self.registry.list().await;
+                let response = ListServersResponse { servers };
+                Ok((0, 0, vec![], bincode::serialize(&response)?))
+            }
+            OperationType::Unknown => Ok((-1, 0, vec![], vec![])),
+        }
+    }
+}
+
+/// Starts the manager, binding `address` and reaping servers that have
+/// gone quiet past their declared lifetime every `reap_interval`.
+pub async fn run(
+    address: String,
+    reap_interval: Duration,
+    tls: Option<TlsMaterial>,
+) -> anyhow::Result<()> {
+    let registry = Arc::new(Registry::new());
+    spawn_reaper(registry.clone(), reap_interval);
+
+    let handler = Arc::new(ManagerHandler { registry });
+    let server = match tls {
+        Some(material) => Server::new_with_tls(handler, &address, &material)?,
+        None => Server::new(handler, &address),
+    };
+    server.run().await
+}
+
+fn spawn_reaper(registry: Arc<Registry>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            registry.reap().await;
+        }
+    });
+}
@@ -0,0 +1,147 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracks every server that has sent the manager a heartbeat: when it was
+//! last seen, what lifetime it asked for, and whether a background reaper
+//! has since declared it dead. Gives `all_servers_address` a dynamic,
+//! authoritative source instead of a static config list.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::Mutex;
+
+use crate::manager::manager_service::{ServerInfo, ServerState};
+
+struct Entry {
+    flags: u32,
+    lifetime: Duration,
+    last_seen: SystemTime,
+    state: ServerState,
+}
+
+#[derive(Default)]
+pub struct Registry {
+    servers: Mutex<HashMap<String, Entry>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records (or refreshes) a heartbeat from `address`, resetting it to
+    /// `Alive` even if a previous reap pass had marked it `Dead`.
+    pub async fn record_heartbeat(&self, address: String, flags: u32, lifetime: Duration) {
+        let mut servers = self.servers.lock().await;
+        servers.insert(
+            address,
+            Entry {
+                flags,
+                lifetime,
+                last_seen: SystemTime::now(),
+                state: ServerState::Alive,
+            },
+        );
+    }
+
+    /// The current view of every server the manager has heard from,
+    /// including ones a reap pass has marked dead but not yet dropped.
+    pub async fn list(&self) -> Vec<ServerInfo> {
+        let servers = self.servers.lock().await;
+        servers
+            .iter()
+            .map(|(address, entry)| ServerInfo {
+                address: address.clone(),
+                flags: entry.flags,
+                last_seen_unix_secs: entry
+                    .last_seen
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                state: entry.state,
+            })
+            .collect()
+    }
+
+    /// Marks servers that have gone quiet past their declared lifetime as
+    /// dead, and drops entries a previous pass already marked dead. Giving
+    /// dead entries one extra pass before removal lets `list` observe the
+    /// `Dead` state at least once.
+    pub async fn reap(&self) {
+        let mut servers = self.servers.lock().await;
+        let now = SystemTime::now();
+        servers.retain(|_, entry| {
+            if entry.state == ServerState::Dead {
+                return false;
+            }
+            if now.duration_since(entry.last_seen).unwrap_or_default() > entry.lifetime {
+                entry.state = ServerState::Dead;
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn record_heartbeat_lists_a_server_as_alive() {
+        let registry = Registry::new();
+        registry
+            .record_heartbeat("127.0.0.1:8001".to_string(), 1, Duration::from_secs(20))
+            .await;
+
+        let servers = registry.list().await;
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].address, "127.0.0.1:8001");
+        assert_eq!(servers[0].state, ServerState::Alive);
+    }
+
+    #[tokio::test]
+    async fn reap_marks_a_stale_server_dead_but_keeps_it_listed_once() {
+        let registry = Registry::new();
+        registry
+            .record_heartbeat("127.0.0.1:8001".to_string(), 1, Duration::from_millis(1))
+            .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        registry.reap().await;
+        let servers = registry.list().await;
+        assert_eq!(servers.len(), 1, "a newly dead server gets one more pass");
+        assert_eq!(servers[0].state, ServerState::Dead);
+    }
+
+    #[tokio::test]
+    async fn reap_drops_a_server_already_marked_dead() {
+        let registry = Registry::new();
+        registry
+            .record_heartbeat("127.0.0.1:8001".to_string(), 1, Duration::from_millis(1))
+            .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        registry.reap().await;
+        registry.reap().await;
+        assert!(registry.list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_heartbeat_after_being_marked_dead_revives_a_server() {
+        let registry = Registry::new();
+        registry
+            .record_heartbeat("127.0.0.1:8001".to_string(), 1, Duration::from_millis(1))
+            .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        registry.reap().await;
+
+        registry
+            .record_heartbeat("127.0.0.1:8001".to_string(), 1, Duration::from_secs(20))
+            .await;
+        let servers = registry.list().await;
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].state, ServerState::Alive);
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::anyhow;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use log::debug;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::Mutex,
+};
+use tokio_rustls::{rustls::ServerName, TlsConnector};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::common::serialization::{
+    read_chunk, read_response_header, write_chunk, write_request, write_stream_end, STREAM_FLAG,
+};
+use crate::rpc::tls::{self, TlsMaterial};
+use crate::rpc::ByteStream;
+
+/// Blanket marker for anything `call_remote` can read/write over, so a
+/// plain `TcpStream` and a `tokio-rustls` stream can sit side by side in
+/// the same connection registry.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// A handle to a live peer connection, serialized because `call_remote`
+/// writes a request and reads its response as one exchange.
+type SharedConn = Arc<Mutex<Box<dyn Connection>>>;
+
+/// Async RPC client used both by the server (to talk to the manager and to
+/// peer servers) and by CLI tools. Connections are kept open and reused,
+/// keyed by peer address.
+pub struct ClientAsync {
+    connections: Mutex<HashMap<String, SharedConn>>,
+    tls: Option<TlsMaterial>,
+}
+
+impl Default for ClientAsync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientAsync {
+    pub fn new() -> Self {
+        Self {
+            connections: Mutex::new(HashMap::new()),
+            tls: None,
+        }
+    }
+
+    /// A client that verifies every peer it connects to against `material`
+    /// instead of speaking plaintext TCP.
+    pub fn new_with_tls(material: TlsMaterial) -> Self {
+        Self {
+            connections: Mutex::new(HashMap::new()),
+            tls: Some(material),
+        }
+    }
+
+    /// Opens (or replaces) the connection to `address`, performing a TLS
+    /// handshake first when the client was built with `new_with_tls`.
+    pub async fn add_connection(&self, address: &str) -> anyhow::Result<()> {
+        let tcp_stream = TcpStream::connect(address).await?;
+        let conn: Box<dyn Connection> = match &self.tls {
+            Some(material) => {
+                let config = tls::client_config(material)?;
+                let connector = TlsConnector::from(Arc::new(config));
+                let server_name = ServerName::try_from(host_of(address))
+                    .map_err(|_| anyhow!("invalid server name: {address}"))?;
+                Box::new(connector.connect(server_name, tcp_stream).await?)
+            }
+            None => Box::new(tcp_stream),
+        };
+        self.connections
+            .lock()
+            .await
+            .insert(address.to_string(), Arc::new(Mutex::new(conn)));
+        debug!("connected to {address}");
+        Ok(())
+    }
+
+    /// Sends one request to `server_address` and fills in the response.
+    /// The response's metadata/data are truncated to fit `recv_meta_data`/
+    /// `recv_data` if the caller's buffers are smaller; `recv_meta_data_length`/
+    /// `recv_data_length` always report the real size so callers can detect
+    /// that.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn call_remote(
+        &self,
+        server_address: &str,
+        operation_type: u32,
+        flags: u32,
+        path: &str,
+        data: &[u8],
+        metadata: &[u8],
+        status: &mut i32,
+        rsp_flags: &mut u32,
+        recv_meta_data_length: &mut usize,
+        recv_data_length: &mut usize,
+        recv_meta_data: &mut [u8],
+        recv_data: &mut [u8],
+    ) -> anyhow::Result<()> {
+        let conn = {
+            let connections = self.connections.lock().await;
+            connections
+                .get(server_address)
+                .cloned()
+                .ok_or_else(|| anyhow!("no connection to {server_address}"))?
+        };
+        let mut stream = conn.lock().await;
+
+        write_request(
+            &mut *stream,
+            operation_type,
+            flags,
+            path.as_bytes(),
+            metadata,
+            data,
+        )
+        .await?;
+
+        let header = read_response_header(&mut *stream).await?;
+        *status = header.status;
+        *rsp_flags = header.rsp_flags;
+        *recv_meta_data_length = header.meta_data_len as usize;
+        *recv_data_length = header.data_len as usize;
+
+        read_truncated(&mut *stream, header.meta_data_len as usize, recv_meta_data).await?;
+        read_truncated(&mut *stream, header.data_len as usize, recv_data).await?;
+
+        Ok(())
+    }
+
+    /// Streaming counterpart of `call_remote`: writes `request_chunks` as
+    /// the request body instead of one buffer, and returns the response
+    /// body as a lazily-polled stream instead of requiring it all up
+    /// front. The peer's handler must implement
+    /// `Handler::dispatch_stream`; the filesystem server behind
+    /// `server::run` does not, and answers with the default error.
+    pub async fn call_remote_stream(
+        &self,
+        server_address: &str,
+        operation_type: u32,
+        flags: u32,
+        path: &str,
+        metadata: &[u8],
+        mut request_chunks: impl Stream<Item = Bytes> + Unpin + Send + 'static,
+    ) -> anyhow::Result<(i32, u32, Vec<u8>, ByteStream)> {
+        let conn = {
+            let connections = self.connections.lock().await;
+            connections
+                .get(server_address)
+                .cloned()
+                .ok_or_else(|| anyhow!("no connection to {server_address}"))?
+        };
+
+        let path = path.as_bytes().to_vec();
+        let metadata = metadata.to_vec();
+        let (header_tx, header_rx) = tokio::sync::oneshot::channel();
+        let (chunk_tx, chunk_rx) = tokio::sync::mpsc::channel(16);
+
+        // Holds the connection lock for the whole exchange so no other
+        // caller's request/response can interleave with this stream.
+        tokio::spawn(async move {
+            let mut stream = conn.lock().await;
+            let result: anyhow::Result<()> = async {
+                write_request(
+                    &mut *stream,
+                    operation_type,
+                    flags | STREAM_FLAG,
+                    &path,
+                    &metadata,
+                    &[],
+                )
+                .await?;
+                while let Some(chunk) = request_chunks.next().await {
+                    write_chunk(&mut *stream, &chunk).await?;
+                }
+                write_stream_end(&mut *stream).await?;
+
+                let header = read_response_header(&mut *stream).await?;
+                let mut rsp_metadata = vec![0u8; header.meta_data_len as usize];
+                tokio::io::AsyncReadExt::read_exact(&mut *stream, &mut rsp_metadata).await?;
+                let _ = header_tx.send((header.status, header.rsp_flags, rsp_metadata));
+
+                while let Some(chunk) = read_chunk(&mut *stream).await? {
+                    if chunk_tx.send(Ok(Bytes::from(chunk))).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            .await;
+
+            if let Err(e) = result {
+                let _ = chunk_tx.send(Err(e)).await;
+            }
+        });
+
+        let (status, rsp_flags, rsp_metadata) = header_rx
+            .await
+            .map_err(|_| anyhow!("connection to {server_address} closed before response header"))?;
+        let body: ByteStream = Box::pin(ReceiverStream::new(chunk_rx));
+        Ok((status, rsp_flags, rsp_metadata, body))
+    }
+}
+
+/// Reads exactly `len` bytes off the wire, copying as much as fits into
+/// `buf` and discarding the rest, so a response larger than the caller's
+/// buffer doesn't desynchronize the stream for the next request.
+async fn read_truncated<S>(stream: &mut S, len: usize, buf: &mut [u8]) -> anyhow::Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let copy_len = len.min(buf.len());
+    stream.read_exact(&mut buf[..copy_len]).await?;
+    if len > copy_len {
+        let mut discard = vec![0u8; len - copy_len];
+        stream.read_exact(&mut discard).await?;
+    }
+    Ok(())
+}
+
+fn host_of(address: &str) -> &str {
+    address.rsplit_once(':').map_or(address, |(host, _)| host)
+}
@@ -0,0 +1,16 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures::Stream;
+
+pub mod client;
+pub mod server;
+pub mod tls;
+
+/// A request or response body delivered incrementally instead of as one
+/// buffered `Vec<u8>`, used by the streaming dispatch path.
+pub type ByteStream = Pin<Box<dyn Stream<Item = anyhow::Result<Bytes>> + Send>>;
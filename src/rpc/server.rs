@@ -0,0 +1,203 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_stream::try_stream;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{debug, error};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::Mutex,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::common::serialization::{
+    read_chunk, read_request_header, write_chunk, write_response, write_stream_end, STREAM_FLAG,
+};
+use crate::rpc::tls::{self, TlsMaterial};
+use crate::rpc::ByteStream;
+
+/// Implemented by whatever knows how to turn a decoded request into a
+/// response. `Server` only deals with transport and framing; every
+/// interpretation of `operation_type` happens here.
+#[async_trait]
+pub trait Handler {
+    async fn dispatch(
+        &self,
+        operation_type: u32,
+        flags: u32,
+        path: Vec<u8>,
+        data: Vec<u8>,
+        metadata: Vec<u8>,
+    ) -> anyhow::Result<(i32, u32, Vec<u8>, Vec<u8>)>;
+
+    /// Streaming counterpart of `dispatch` for operations whose data
+    /// portion is too large to buffer whole, such as large file reads and
+    /// writes. Dispatched instead of `dispatch` when the request's `flags`
+    /// carry `STREAM_FLAG`. Handlers that don't support it can rely on
+    /// this default.
+    async fn dispatch_stream(
+        &self,
+        operation_type: u32,
+        _flags: u32,
+        _path: Vec<u8>,
+        _metadata: Vec<u8>,
+        _data: ByteStream,
+    ) -> anyhow::Result<(i32, u32, Vec<u8>, ByteStream)> {
+        Err(anyhow!(
+            "operation {operation_type} does not support streaming dispatch"
+        ))
+    }
+}
+
+pub struct Server {
+    handler: Arc<dyn Handler + Send + Sync>,
+    address: String,
+    tls_acceptor: Option<TlsAcceptor>,
+}
+
+impl Server {
+    /// Plaintext TCP server. This is still the default: `enable_tls` in
+    /// `Properties` is what decides whether `new_with_tls` is used instead.
+    pub fn new(handler: Arc<dyn Handler + Send + Sync>, address: &str) -> Self {
+        Self {
+            handler,
+            address: address.to_string(),
+            tls_acceptor: None,
+        }
+    }
+
+    /// A server that terminates TLS on every accepted connection, via a
+    /// handshake, before any bytes reach `Handler::dispatch`.
+    pub fn new_with_tls(
+        handler: Arc<dyn Handler + Send + Sync>,
+        address: &str,
+        material: &TlsMaterial,
+    ) -> anyhow::Result<Self> {
+        let config = tls::server_config(material)?;
+        Ok(Self {
+            handler,
+            address: address.to_string(),
+            tls_acceptor: Some(TlsAcceptor::from(Arc::new(config))),
+        })
+    }
+
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(&self.address).await?;
+        debug!("server listening on {}", self.address);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let handler = self.handler.clone();
+            match self.tls_acceptor.clone() {
+                Some(acceptor) => {
+                    tokio::spawn(async move {
+                        match acceptor.accept(stream).await {
+                            Ok(tls_stream) => {
+                                if let Err(e) = serve_connection(tls_stream, handler).await {
+                                    error!("connection from {} failed: {:?}", peer, e);
+                                }
+                            }
+                            Err(e) => error!("tls handshake with {} failed: {:?}", peer, e),
+                        }
+                    });
+                }
+                None => {
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_connection(stream, handler).await {
+                            error!("connection from {} failed: {:?}", peer, e);
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Serves requests off one connection until the peer closes it. Generic
+/// over the stream type so the same loop drives a raw `TcpStream` or a
+/// `tokio-rustls` stream without duplicating the framing logic. The
+/// connection is wrapped in a shared lock so a streaming request's body
+/// can be read lazily, chunk by chunk, while the handler is awaiting it.
+async fn serve_connection<S>(stream: S, handler: Arc<dyn Handler + Send + Sync>) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    use tokio::io::AsyncReadExt;
+
+    let conn = Arc::new(Mutex::new(stream));
+    loop {
+        let (header, path, metadata) = {
+            let mut stream = conn.lock().await;
+            let header = match read_request_header(&mut *stream).await {
+                Ok(header) => header,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let mut path = vec![0u8; header.path_len as usize];
+            stream.read_exact(&mut path).await?;
+            let mut metadata = vec![0u8; header.meta_data_len as usize];
+            stream.read_exact(&mut metadata).await?;
+            (header, path, metadata)
+        };
+
+        if header.flags & STREAM_FLAG != 0 {
+            let request_body = chunk_stream(conn.clone());
+            let (status, rsp_flags, rsp_metadata, mut rsp_body) = handler
+                .dispatch_stream(
+                    header.operation_type,
+                    header.flags,
+                    path,
+                    metadata,
+                    request_body,
+                )
+                .await?;
+
+            let mut stream = conn.lock().await;
+            write_response(&mut *stream, status, rsp_flags, &rsp_metadata, &[]).await?;
+            while let Some(chunk) = rsp_body.next().await {
+                write_chunk(&mut *stream, &chunk?).await?;
+            }
+            write_stream_end(&mut *stream).await?;
+        } else {
+            let mut data = vec![0u8; header.data_len as usize];
+            {
+                let mut stream = conn.lock().await;
+                stream.read_exact(&mut data).await?;
+            }
+
+            let (status, rsp_flags, rsp_metadata, rsp_data) = handler
+                .dispatch(header.operation_type, header.flags, path, data, metadata)
+                .await?;
+
+            let mut stream = conn.lock().await;
+            write_response(&mut *stream, status, rsp_flags, &rsp_metadata, &rsp_data).await?;
+        }
+    }
+}
+
+/// Wraps the shared connection as a lazily-polled chunk stream so
+/// `Handler::dispatch_stream` can read the request body incrementally
+/// instead of having it all buffered up front.
+fn chunk_stream<S>(conn: Arc<Mutex<S>>) -> ByteStream
+where
+    S: AsyncRead + Unpin + Send + 'static,
+{
+    Box::pin(try_stream! {
+        loop {
+            let mut stream = conn.lock().await;
+            match read_chunk(&mut *stream).await? {
+                Some(chunk) => {
+                    drop(stream);
+                    yield Bytes::from(chunk);
+                }
+                None => break,
+            }
+        }
+    })
+}
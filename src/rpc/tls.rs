@@ -0,0 +1,76 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Shared `rustls` config loading for the RPC client and server. Kept in
+//! one place so both sides parse cert/key/CA material the same way.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use tokio_rustls::rustls;
+
+/// Cert chain, private key and optional CA root used to terminate or
+/// originate TLS on an RPC connection. `enable_tls: false` in `Properties`
+/// means this is never constructed and the connection stays plaintext.
+#[derive(Debug, Clone)]
+pub struct TlsMaterial {
+    pub cert_path: String,
+    pub key_path: String,
+    pub ca_path: Option<String>,
+}
+
+pub fn server_config(material: &TlsMaterial) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = load_certs(Path::new(&material.cert_path))?;
+    let key = load_key(Path::new(&material.key_path))?;
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(config)
+}
+
+/// Builds a client config that verifies the peer against `ca_path` when
+/// present, falling back to the platform's native root store otherwise.
+pub fn client_config(material: &TlsMaterial) -> anyhow::Result<rustls::ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    match &material.ca_path {
+        Some(ca_path) => {
+            for cert in load_certs(Path::new(ca_path))? {
+                roots.add(&cert)?;
+            }
+        }
+        None => {
+            for cert in
+                rustls_native_certs::load_native_certs().map_err(|e| anyhow!("{e}"))?
+            {
+                roots.add(&rustls::Certificate(cert.0))?;
+            }
+        }
+    }
+    Ok(rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<rustls::Certificate>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("failed to open cert file {}: {e}", path.display()))?;
+    let mut reader = std::io::BufReader::new(file);
+    Ok(rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect())
+}
+
+fn load_key(path: &Path) -> anyhow::Result<rustls::PrivateKey> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("failed to open key file {}: {e}", path.display()))?;
+    let mut reader = std::io::BufReader::new(file);
+    let key = rustls_pemfile::pkcs8_private_keys(&mut reader)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no PKCS#8 private key found in {}", path.display()))?;
+    Ok(rustls::PrivateKey(key))
+}
@@ -0,0 +1,71 @@
+// Copyright 2022 labring. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The filesystem server: binds the RPC transport to a `Handler` that
+//! understands filesystem operations.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{info, warn};
+use tokio::sync::RwLock;
+
+use crate::rpc::server::{Handler, Server};
+use crate::rpc::tls::TlsMaterial;
+
+struct FsHandler {
+    #[allow(dead_code)]
+    database_path: String,
+    #[allow(dead_code)]
+    storage_path: String,
+    /// The manager's current view of every server, refreshed in the
+    /// background by `bin/server.rs` via `ListServers` instead of staying
+    /// pinned to the static config value it started with.
+    #[allow(dead_code)]
+    all_servers_address: Arc<RwLock<Vec<String>>>,
+}
+
+#[async_trait]
+impl Handler for FsHandler {
+    async fn dispatch(
+        &self,
+        operation_type: u32,
+        _flags: u32,
+        _path: Vec<u8>,
+        _data: Vec<u8>,
+        _metadata: Vec<u8>,
+    ) -> anyhow::Result<(i32, u32, Vec<u8>, Vec<u8>)> {
+        // No filesystem operation is implemented here. Every request is
+        // answered with status -1 instead of panicking, so the connection
+        // stays open for the next request.
+        warn!("filesystem operation {operation_type} is not implemented yet");
+        Ok((-1, 0, vec![], vec![]))
+    }
+}
+
+/// Starts the filesystem server, binding `server_address`. The handler
+/// keeps `database_path`/`storage_path` but does not read or write them:
+/// every one-shot request is answered with status -1, and streaming
+/// requests get the default `Handler::dispatch_stream` error. When `tls`
+/// is `Some`, every accepted connection is required to complete a TLS
+/// handshake before it reaches the handler.
+pub async fn run(
+    database_path: String,
+    storage_path: String,
+    server_address: String,
+    all_servers_address: Arc<RwLock<Vec<String>>>,
+    tls: Option<TlsMaterial>,
+) -> anyhow::Result<()> {
+    let handler = Arc::new(FsHandler {
+        database_path,
+        storage_path,
+        all_servers_address,
+    });
+    info!("filesystem server listening on {server_address}");
+    let server = match tls {
+        Some(material) => Server::new_with_tls(handler, &server_address, &material)?,
+        None => Server::new(handler, &server_address),
+    };
+    server.run().await
+}